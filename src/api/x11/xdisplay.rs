@@ -2,8 +2,12 @@ use std::ptr;
 use std::fmt;
 use std::error::Error;
 use std::ffi::CString;
+use std::collections::HashMap;
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicPtr, Ordering};
 
 use libc;
+use libc::c_int;
 
 use super::ffi;
 use api::egl::ffi::egl::Egl;
@@ -15,9 +19,31 @@ pub struct XConnection {
     pub xf86vmode: ffi::Xf86vmode,
     pub xcursor: ffi::Xcursor,
     pub xinput2: ffi::XInput2,
+    pub xrandr: Option<ffi::Xrandr>,
     pub glx: Option<ffi::glx::Glx>,
     pub egl: Option<Egl>,
     pub display: *mut ffi::Display,
+    /// The X server socket, as returned by `XConnectionNumber`. Lets
+    /// callers register the connection with `epoll`/`poll`/`mio` and
+    /// dispatch X events from within a larger event loop instead of
+    /// busy-waiting on `XPending`.
+    pub x11_fd: c_int,
+    pub xlib_xcb: Option<ffi::XlibXCB>,
+    pub xrender: Option<ffi::Xrender>,
+    /// The most recent X protocol error reported through the error handler
+    /// installed in `new()`. Many GLX calls only report failure this way,
+    /// so `check_errors()` lets callers turn it into a proper `Result`.
+    ///
+    /// Boxed so its address stays stable even if the `XConnection` itself
+    /// is moved, since the process-global error handler keeps a raw
+    /// pointer to it (see `ErrorHandlerContext`).
+    pub latest_error: Box<Mutex<Option<XError>>>,
+    /// Cursors loaded through `get_cursor()`, keyed by the icon they were
+    /// loaded for so repeated sets of the same icon are cheap.
+    pub cursor_cache: Mutex<HashMap<Option<CursorIcon>, ffi::Cursor>>,
+    /// This connection's entry in `error_handler_registry()`, torn down in
+    /// `Drop` so the process-global error handler stops referencing it.
+    error_handler_context: *mut ErrorHandlerContext,
 }
 
 unsafe impl Send for XConnection {}
@@ -25,6 +51,66 @@ unsafe impl Sync for XConnection {}
 
 pub type XErrorHandler = Option<unsafe extern fn(*mut ffi::Display, *mut ffi::XErrorEvent) -> libc::c_int>;
 
+/// Holds what the process-global error handler needs in order to turn an
+/// `XErrorEvent` into a `XError` and store it on the right `XConnection`.
+/// `XSetErrorHandler` takes a plain C function pointer with no userdata
+/// and applies to every `Display` in the process, so dispatching has to go
+/// through `error_handler_registry()` rather than a single static context.
+struct ErrorHandlerContext {
+    get_error_text: unsafe extern fn(*mut ffi::Display, c_int, *mut libc::c_char, c_int) -> c_int,
+    latest_error: *const Mutex<Option<XError>>,
+}
+
+unsafe impl Send for ErrorHandlerContext {}
+unsafe impl Sync for ErrorHandlerContext {}
+
+/// Maps a live `Display` pointer (as a `usize`) to the `ErrorHandlerContext`
+/// of the `XConnection` that owns it. Entries are added in `new()` and
+/// removed in `Drop`, so a connection's errors stop being dispatched (and
+/// its freed `latest_error` stops being touched) the moment it's dropped,
+/// even if other connections are still alive and using the same
+/// process-global handler.
+static REGISTRY_INIT: Once = ONCE_INIT;
+static REGISTRY_PTR: AtomicPtr<Mutex<HashMap<usize, *mut ErrorHandlerContext>>> = AtomicPtr::new(ptr::null_mut());
+
+fn error_handler_registry() -> &'static Mutex<HashMap<usize, *mut ErrorHandlerContext>> {
+    REGISTRY_INIT.call_once(|| {
+        let registry = Box::new(Mutex::new(HashMap::new()));
+        REGISTRY_PTR.store(Box::into_raw(registry), Ordering::SeqCst);
+    });
+
+    unsafe { &*REGISTRY_PTR.load(Ordering::SeqCst) }
+}
+
+unsafe extern fn x_error_callback(display: *mut ffi::Display, event: *mut ffi::XErrorEvent) -> libc::c_int {
+    let registry = match error_handler_registry().lock() {
+        Ok(registry) => registry,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    if let Some(&context) = registry.get(&(display as usize)) {
+        let event = &*event;
+
+        let mut buffer = [0 as libc::c_char; 256];
+        ((*context).get_error_text)(display, event.error_code as c_int, buffer.as_mut_ptr(), buffer.len() as c_int);
+        let description = ::std::ffi::CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned();
+
+        let latest_error = &*(*context).latest_error;
+        let mut latest_error = match latest_error.lock() {
+            Ok(latest_error) => latest_error,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *latest_error = Some(XError {
+            description: description,
+            error_code: event.error_code,
+            request_code: event.request_code,
+            minor_code: event.minor_code,
+        });
+    }
+
+    0
+}
+
 impl XConnection {
     pub fn new(error_handler: XErrorHandler) -> Result<XConnection, XNotSupported> {
         // opening the libraries
@@ -34,7 +120,10 @@ impl XConnection {
         let xinput2 = try!(ffi::XInput2::open());
 
         unsafe { (xlib.XInitThreads)() };
-        unsafe { (xlib.XSetErrorHandler)(error_handler) };
+
+        // the caller's handler takes priority if provided; otherwise we
+        // install our own, which records errors into `latest_error`
+        unsafe { (xlib.XSetErrorHandler)(error_handler.or(Some(x_error_callback))) };
 
         // TODO: use something safer than raw "dlopen"
         let glx = {
@@ -53,6 +142,23 @@ impl XConnection {
             }
         };
 
+        // TODO: use something safer than raw "dlopen"
+        let xrandr = {
+            let mut libxrandr = unsafe { dlopen::dlopen(b"libXrandr.so.2\0".as_ptr() as *const _, dlopen::RTLD_NOW) };
+            if libxrandr.is_null() {
+                libxrandr = unsafe { dlopen::dlopen(b"libXrandr.so\0".as_ptr() as *const _, dlopen::RTLD_NOW) };
+            }
+
+            if libxrandr.is_null() {
+                None
+            } else {
+                Some(ffi::Xrandr::load_with(|sym| {
+                    let sym = CString::new(sym).unwrap();
+                    unsafe { dlopen::dlsym(libxrandr, sym.as_ptr()) }
+                }))
+            }
+        };
+
         // TODO: use something safer than raw "dlopen"
         let egl = {
             let mut libegl = unsafe { dlopen::dlopen(b"libEGL.so.1\0".as_ptr() as *const _, dlopen::RTLD_NOW) };
@@ -70,6 +176,40 @@ impl XConnection {
             }
         };
 
+        // TODO: use something safer than raw "dlopen"
+        let xlib_xcb = {
+            let mut libxlibxcb = unsafe { dlopen::dlopen(b"libX11-xcb.so.1\0".as_ptr() as *const _, dlopen::RTLD_NOW) };
+            if libxlibxcb.is_null() {
+                libxlibxcb = unsafe { dlopen::dlopen(b"libX11-xcb.so\0".as_ptr() as *const _, dlopen::RTLD_NOW) };
+            }
+
+            if libxlibxcb.is_null() {
+                None
+            } else {
+                Some(ffi::XlibXCB::load_with(|sym| {
+                    let sym = CString::new(sym).unwrap();
+                    unsafe { dlopen::dlsym(libxlibxcb, sym.as_ptr()) }
+                }))
+            }
+        };
+
+        // TODO: use something safer than raw "dlopen"
+        let xrender = {
+            let mut libxrender = unsafe { dlopen::dlopen(b"libXrender.so.1\0".as_ptr() as *const _, dlopen::RTLD_NOW) };
+            if libxrender.is_null() {
+                libxrender = unsafe { dlopen::dlopen(b"libXrender.so\0".as_ptr() as *const _, dlopen::RTLD_NOW) };
+            }
+
+            if libxrender.is_null() {
+                None
+            } else {
+                Some(ffi::Xrender::load_with(|sym| {
+                    let sym = CString::new(sym).unwrap();
+                    unsafe { dlopen::dlsym(libxrender, sym.as_ptr()) }
+                }))
+            }
+        };
+
         // calling XOpenDisplay
         let display = unsafe {
             let display = (xlib.XOpenDisplay)(ptr::null());
@@ -79,25 +219,514 @@ impl XConnection {
             display
         };
 
+        let x11_fd = unsafe { (xlib.XConnectionNumber)(display) };
+
+        let latest_error = Box::new(Mutex::new(None));
+        let error_handler_context = Box::into_raw(Box::new(ErrorHandlerContext {
+            get_error_text: xlib.XGetErrorText,
+            latest_error: &*latest_error as *const _,
+        }));
+        error_handler_registry().lock().unwrap().insert(display as usize, error_handler_context);
+
         Ok(XConnection {
             xlib: xlib,
             xf86vmode: xf86vmode,
             xcursor: xcursor,
             xinput2: xinput2,
+            xrandr: xrandr,
             glx: glx,
             egl: egl,
             display: display,
+            x11_fd: x11_fd,
+            xlib_xcb: xlib_xcb,
+            xrender: xrender,
+            latest_error: latest_error,
+            cursor_cache: Mutex::new(HashMap::new()),
+            error_handler_context: error_handler_context,
         })
     }
+
+    /// Atomically takes and returns the most recent X protocol error, if
+    /// one has been reported since the last call.
+    pub fn check_errors(&self) -> Option<XError> {
+        self.latest_error.lock().unwrap().take()
+    }
+
+    /// The file descriptor of the connection's X server socket.
+    #[inline]
+    pub fn x11_fd(&self) -> c_int {
+        self.x11_fd
+    }
+
+    /// Looks for a 32-bit ARGB visual on `screen_num`, i.e. one whose
+    /// `PictFormat` has a nonzero alpha mask, so that a window created with
+    /// it can be composited with real per-pixel transparency under a
+    /// running compositor. Without this glutin can only ever hand out
+    /// opaque visuals, so a "transparent" window hint has nothing to honor.
+    ///
+    /// Returns the visual together with a colormap created for it, both of
+    /// which the caller can pass straight into `XCreateWindow`'s
+    /// `XSetWindowAttributes`. `None` if XRender isn't available or the
+    /// screen has no matching visual.
+    pub fn find_argb_visual(&self, screen_num: c_int) -> Option<(*mut ffi::Visual, ffi::Colormap)> {
+        let xrender = match self.xrender {
+            Some(ref xrender) => xrender,
+            None => return None,
+        };
+
+        unsafe {
+            let mut template: ffi::XVisualInfo = ::std::mem::zeroed();
+            template.screen = screen_num;
+
+            let mut n_matches = 0;
+            let infos = (self.xlib.XGetVisualInfo)(
+                self.display, ffi::VisualScreenMask, &mut template, &mut n_matches,
+            );
+            if infos.is_null() {
+                return None;
+            }
+
+            let mut argb_visual = None;
+            for i in 0..n_matches as isize {
+                let info = *infos.offset(i);
+                let format = (xrender.XRenderFindVisualFormat)(self.display, info.visual);
+                if format.is_null() {
+                    continue;
+                }
+
+                if (*format).type_ == ffi::PictTypeDirect && (*format).direct.alphaMask != 0 {
+                    argb_visual = Some(info.visual);
+                    break;
+                }
+            }
+
+            (self.xlib.XFree)(infos as *mut _);
+
+            let visual = match argb_visual {
+                Some(visual) => visual,
+                None => return None,
+            };
+
+            let root = (self.xlib.XRootWindow)(self.display, screen_num);
+            let colormap = (self.xlib.XCreateColormap)(self.display, root, visual, ffi::AllocNone);
+            Some((visual, colormap))
+        }
+    }
+
+    /// Returns the `xcb_connection_t` backing this display, for callers
+    /// that want to dispatch some events through XCB while still using
+    /// Xlib (e.g. via `XSetEventQueueOwner`). `None` if `libX11-xcb` isn't
+    /// available.
+    pub fn get_xcb_connection(&self) -> Option<*mut ffi::xcb_connection_t> {
+        let xlib_xcb = match self.xlib_xcb {
+            Some(ref xlib_xcb) => xlib_xcb,
+            None => return None,
+        };
+
+        let connection = unsafe { (xlib_xcb.XGetXCBConnection)(self.display) };
+        if connection.is_null() {
+            None
+        } else {
+            Some(connection)
+        }
+    }
+
+    /// Returns the `Cursor` for `icon`, loading and caching it on first use.
+    ///
+    /// `None` returns `0`, X's `None` cursor, without loading anything; no
+    /// blank cursor is created for it. Every other variant is first looked up in the current
+    /// Xcursor theme and falls back to the matching legacy X font cursor
+    /// shape if the theme has no matching image.
+    pub fn get_cursor(&self, icon: Option<CursorIcon>) -> ffi::Cursor {
+        let mut cache = self.cursor_cache.lock().unwrap();
+
+        if let Some(&cursor) = cache.get(&icon) {
+            return cursor;
+        }
+
+        let cursor = self.load_cursor(icon);
+        cache.insert(icon, cursor);
+        cursor
+    }
+
+    fn load_cursor(&self, icon: Option<CursorIcon>) -> ffi::Cursor {
+        let icon = match icon {
+            Some(icon) => icon,
+            None => return 0,
+        };
+
+        let name = CString::new(icon.name()).unwrap();
+        let themed = unsafe {
+            (self.xcursor.XcursorLibraryLoadCursor)(self.display, name.as_ptr())
+        };
+
+        if themed != 0 {
+            themed
+        } else {
+            unsafe { (self.xlib.XCreateFontCursor)(self.display, icon.x_font_shape()) }
+        }
+    }
+
+    /// Enumerates the outputs currently connected to the X server.
+    ///
+    /// Prefers the RandR 1.5 `XRRGetMonitors` call, which reports monitor
+    /// geometry directly, and falls back to walking the 1.2+ CRTC/output
+    /// API when the server (or the client-side extension library) is too
+    /// old to support it. Returns an empty vec if RandR isn't available at
+    /// all.
+    pub fn query_monitors(&self) -> Vec<MonitorDescriptor> {
+        let xrandr = match self.xrandr {
+            Some(ref xrandr) => xrandr,
+            None => return Vec::new(),
+        };
+
+        let root = unsafe { (self.xlib.XDefaultRootWindow)(self.display) };
+
+        if let Some(monitors) = self.query_monitors_1_5(xrandr, root) {
+            return monitors;
+        }
+
+        self.query_monitors_1_2(xrandr, root)
+    }
+
+    fn query_monitors_1_5(&self, xrandr: &ffi::Xrandr, root: ffi::Window) -> Option<Vec<MonitorDescriptor>> {
+        let get_monitors = match xrandr.XRRGetMonitors {
+            Some(get_monitors) => get_monitors,
+            None => return None,
+        };
+
+        unsafe {
+            let mut count = 0;
+            let infos = get_monitors(self.display, root, 1, &mut count);
+            if infos.is_null() {
+                return None;
+            }
+
+            // `XRRMonitorInfo` doesn't carry a refresh rate itself, so look
+            // it up the same way the 1.2 fallback does: monitor -> one of
+            // its outputs -> that output's CRTC -> the CRTC's current mode.
+            let resources = (xrandr.XRRGetScreenResources)(self.display, root);
+
+            let mut monitors = Vec::with_capacity(count as usize);
+            for i in 0..count as isize {
+                let info = *infos.offset(i);
+
+                let name_ptr = (self.xlib.XGetAtomName)(self.display, info.name);
+                let name = if name_ptr.is_null() {
+                    String::new()
+                } else {
+                    let name = ::std::ffi::CStr::from_ptr(name_ptr).to_string_lossy().into_owned();
+                    (self.xlib.XFree)(name_ptr as *mut _);
+                    name
+                };
+
+                let refresh_rate = if !resources.is_null() && info.noutput > 0 {
+                    self.monitor_refresh_rate(xrandr, resources, *info.outputs)
+                } else {
+                    0.0
+                };
+
+                monitors.push(MonitorDescriptor {
+                    name: name,
+                    position: (info.x as i32, info.y as i32),
+                    dimensions: (info.width as u32, info.height as u32),
+                    refresh_rate: refresh_rate,
+                    primary: info.primary != 0,
+                });
+            }
+
+            if !resources.is_null() {
+                (xrandr.XRRFreeScreenResources)(resources);
+            }
+
+            (xrandr.XRRFreeMonitors)(infos);
+            Some(monitors)
+        }
+    }
+
+    /// Looks up the refresh rate of `output`'s current mode via its CRTC.
+    unsafe fn monitor_refresh_rate(&self, xrandr: &ffi::Xrandr, resources: *mut ffi::XRRScreenResources, output: ffi::RROutput) -> f32 {
+        let output_info = (xrandr.XRRGetOutputInfo)(self.display, resources, output);
+        if output_info.is_null() {
+            return 0.0;
+        }
+
+        let refresh_rate = if (*output_info).crtc != 0 {
+            let crtc_info = (xrandr.XRRGetCrtcInfo)(self.display, resources, (*output_info).crtc);
+            if !crtc_info.is_null() {
+                let refresh_rate = mode_refresh_rate(resources, (*crtc_info).mode);
+                (xrandr.XRRFreeCrtcInfo)(crtc_info);
+                refresh_rate
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        };
+
+        (xrandr.XRRFreeOutputInfo)(output_info);
+        refresh_rate
+    }
+
+    fn query_monitors_1_2(&self, xrandr: &ffi::Xrandr, root: ffi::Window) -> Vec<MonitorDescriptor> {
+        unsafe {
+            let resources = (xrandr.XRRGetScreenResources)(self.display, root);
+            if resources.is_null() {
+                return Vec::new();
+            }
+
+            let mut monitors = Vec::with_capacity((*resources).noutput as usize);
+
+            for i in 0..(*resources).noutput as isize {
+                let output = *(*resources).outputs.offset(i);
+                let output_info = (xrandr.XRRGetOutputInfo)(self.display, resources, output);
+                if output_info.is_null() {
+                    continue;
+                }
+
+                if (*output_info).connection != ffi::RR_Connected || (*output_info).crtc == 0 {
+                    (xrandr.XRRFreeOutputInfo)(output_info);
+                    continue;
+                }
+
+                let crtc_info = (xrandr.XRRGetCrtcInfo)(self.display, resources, (*output_info).crtc);
+                if !crtc_info.is_null() {
+                    let name = ::std::slice::from_raw_parts((*output_info).name as *const u8, (*output_info).nameLen as usize);
+                    let refresh_rate = mode_refresh_rate(resources, (*crtc_info).mode);
+
+                    monitors.push(MonitorDescriptor {
+                        name: String::from_utf8_lossy(name).into_owned(),
+                        position: ((*crtc_info).x, (*crtc_info).y),
+                        dimensions: ((*crtc_info).width as u32, (*crtc_info).height as u32),
+                        refresh_rate: refresh_rate,
+                        primary: output == (xrandr.XRRGetOutputPrimary)(self.display, root),
+                    });
+
+                    (xrandr.XRRFreeCrtcInfo)(crtc_info);
+                }
+
+                (xrandr.XRRFreeOutputInfo)(output_info);
+            }
+
+            (xrandr.XRRFreeScreenResources)(resources);
+            monitors
+        }
+    }
+
+    /// Switches `crtc` to `mode_id`, for fullscreen video mode changes.
+    ///
+    /// Returns a guard that restores the CRTC's previous mode and position
+    /// when dropped, so a fullscreen window doesn't leave the desktop in a
+    /// broken resolution if it's closed or panics.
+    pub fn set_crtc_mode(&self, crtc: ffi::RRCrtc, mode_id: ffi::RRMode) -> Option<CrtcModeGuard> {
+        let xrandr = match self.xrandr {
+            Some(ref xrandr) => xrandr,
+            None => return None,
+        };
+
+        unsafe {
+            let root = (self.xlib.XDefaultRootWindow)(self.display);
+            let resources = (xrandr.XRRGetScreenResources)(self.display, root);
+            if resources.is_null() {
+                return None;
+            }
+
+            let crtc_info = (xrandr.XRRGetCrtcInfo)(self.display, resources, crtc);
+            if crtc_info.is_null() {
+                (xrandr.XRRFreeScreenResources)(resources);
+                return None;
+            }
+
+            let previous_mode = (*crtc_info).mode;
+            let x = (*crtc_info).x;
+            let y = (*crtc_info).y;
+            let rotation = (*crtc_info).rotation;
+            let noutput = (*crtc_info).noutput;
+            // `XRRGetCrtcInfo` allocates `outputs` as part of the same
+            // block as `crtc_info`, so it's freed by `XRRFreeCrtcInfo`
+            // below; copy it out into an owned `Vec` we can still pass to
+            // `XRRSetCrtcConfig` (and later keep in the guard) afterwards.
+            let outputs: Vec<ffi::RROutput> = ::std::slice::from_raw_parts((*crtc_info).outputs, noutput as usize).to_vec();
+
+            let status = (xrandr.XRRSetCrtcConfig)(
+                self.display, resources, crtc, ffi::CurrentTime,
+                x, y, mode_id, rotation, outputs.as_ptr() as *mut _, noutput,
+            );
+
+            (xrandr.XRRFreeCrtcInfo)(crtc_info);
+            (xrandr.XRRFreeScreenResources)(resources);
+
+            if status != ffi::RRSetConfigSuccess {
+                // the server rejected the switch (stale config timestamp,
+                // mode not valid for this CRTC, ...); nothing to restore
+                return None;
+            }
+
+            Some(CrtcModeGuard {
+                xconn: self,
+                crtc: crtc,
+                previous_mode: previous_mode,
+                x: x,
+                y: y,
+                rotation: rotation,
+                outputs: outputs,
+            })
+        }
+    }
+}
+
+fn mode_refresh_rate(resources: *mut ffi::XRRScreenResources, mode_id: ffi::RRMode) -> f32 {
+    unsafe {
+        for i in 0..(*resources).nmode as isize {
+            let mode_info = *(*resources).modes.offset(i);
+            if mode_info.id == mode_id {
+                if mode_info.hTotal == 0 || mode_info.vTotal == 0 {
+                    return 0.0;
+                }
+                return mode_info.dotClock as f32 / (mode_info.hTotal as f32 * mode_info.vTotal as f32);
+            }
+        }
+        0.0
+    }
+}
+
+/// Describes a connected monitor as reported by the RandR extension.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorDescriptor {
+    pub name: String,
+    pub position: (i32, i32),
+    pub dimensions: (u32, u32),
+    pub refresh_rate: f32,
+    pub primary: bool,
+}
+
+/// Restores a CRTC's previous mode and position when dropped.
+pub struct CrtcModeGuard<'a> {
+    xconn: &'a XConnection,
+    crtc: ffi::RRCrtc,
+    previous_mode: ffi::RRMode,
+    x: i32,
+    y: i32,
+    rotation: ffi::Rotation,
+    outputs: Vec<ffi::RROutput>,
+}
+
+impl<'a> Drop for CrtcModeGuard<'a> {
+    fn drop(&mut self) {
+        let xrandr = match self.xconn.xrandr {
+            Some(ref xrandr) => xrandr,
+            None => return,
+        };
+
+        unsafe {
+            let root = (self.xconn.xlib.XDefaultRootWindow)(self.xconn.display);
+            let resources = (xrandr.XRRGetScreenResources)(self.xconn.display, root);
+            if resources.is_null() {
+                return;
+            }
+
+            // best-effort restore: this is a `Drop` impl, so there's no
+            // `Result` to report a rejected `Status` through, and nothing
+            // else useful to do if the server refuses it here
+            (xrandr.XRRSetCrtcConfig)(
+                self.xconn.display, resources, self.crtc, ffi::CurrentTime,
+                self.x, self.y, self.previous_mode, self.rotation,
+                self.outputs.as_ptr() as *mut _, self.outputs.len() as i32,
+            );
+
+            (xrandr.XRRFreeScreenResources)(resources);
+        }
+    }
 }
 
 impl Drop for XConnection {
-    #[inline]
     fn drop(&mut self) {
+        // Deregister before closing the display so the process-global error
+        // handler can never look up this connection's context again, then
+        // free it -- otherwise it's a dangling `latest_error` pointer that
+        // a later error on another connection's `Display` could dispatch
+        // into. `XSetErrorHandler` itself stays installed; it just becomes
+        // a no-op for this connection after this point.
+        error_handler_registry().lock().unwrap().remove(&(self.display as usize));
+        unsafe { Box::from_raw(self.error_handler_context) };
+
+        for (_, &cursor) in self.cursor_cache.lock().unwrap().iter() {
+            if cursor != 0 {
+                unsafe { (self.xlib.XFreeCursor)(self.display, cursor) };
+            }
+        }
+
         unsafe { (self.xlib.XCloseDisplay)(self.display) };
     }
 }
 
+/// A themed mouse cursor shape, resolved to a system cursor by
+/// `XConnection::get_cursor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CursorIcon {
+    Arrow,
+    Hand,
+    Text,
+    Crosshair,
+    Wait,
+    NotAllowed,
+    ResizeN,
+    ResizeS,
+    ResizeE,
+    ResizeW,
+}
+
+impl CursorIcon {
+    /// The Xcursor theme name to look up first.
+    fn name(&self) -> &'static str {
+        match *self {
+            CursorIcon::Arrow => "default",
+            CursorIcon::Hand => "pointer",
+            CursorIcon::Text => "text",
+            CursorIcon::Crosshair => "crosshair",
+            CursorIcon::Wait => "wait",
+            CursorIcon::NotAllowed => "not-allowed",
+            CursorIcon::ResizeN => "n-resize",
+            CursorIcon::ResizeS => "s-resize",
+            CursorIcon::ResizeE => "e-resize",
+            CursorIcon::ResizeW => "w-resize",
+        }
+    }
+
+    /// The legacy `XCreateFontCursor` shape to fall back to when the theme
+    /// has no cursor under `name()`.
+    fn x_font_shape(&self) -> libc::c_uint {
+        match *self {
+            CursorIcon::Arrow => ffi::XC_left_ptr,
+            CursorIcon::Hand => ffi::XC_hand2,
+            CursorIcon::Text => ffi::XC_xterm,
+            CursorIcon::Crosshair => ffi::XC_crosshair,
+            CursorIcon::Wait => ffi::XC_watch,
+            CursorIcon::NotAllowed => ffi::XC_X_cursor,
+            CursorIcon::ResizeN => ffi::XC_top_side,
+            CursorIcon::ResizeS => ffi::XC_bottom_side,
+            CursorIcon::ResizeE => ffi::XC_right_side,
+            CursorIcon::ResizeW => ffi::XC_left_side,
+        }
+    }
+}
+
+/// An X protocol error, captured by the error handler installed in
+/// `XConnection::new`.
+#[derive(Clone, Debug)]
+pub struct XError {
+    pub description: String,
+    pub error_code: u8,
+    pub request_code: u8,
+    pub minor_code: u8,
+}
+
+impl fmt::Display for XError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        formatter.write_str(&self.description)
+    }
+}
+
 /// Error returned if this system doesn't have XLib or can't create an X connection.
 #[derive(Clone, Debug)]
 pub enum XNotSupported {